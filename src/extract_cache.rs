@@ -0,0 +1,205 @@
+use std::collections::hash_map::DefaultHasher;
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::io::{Cursor, Read, Write};
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::SystemTime;
+
+use anyhow::Context;
+use bytesize::ByteSize;
+use log::warn;
+
+use crate::index::file_fingerprint;
+
+/// How extracted member files are stored on disk once decompressed out of their package.
+#[derive(Debug, Clone)]
+pub enum CacheCodec {
+    /// No recompression; fastest reads, largest footprint.
+    Raw,
+    /// zstd favors fast reads over a smaller blob; `window_log` widens the match window for a
+    /// modest ratio improvement at the cost of decoder memory.
+    Zstd {
+        level: i32,
+        window_log: Option<u32>,
+    },
+    /// xz trades CPU for a smaller blob; a larger `dict_size` yields materially smaller cached
+    /// files at a higher one-time compression cost.
+    Xz { level: u32, dict_size: Option<u32> },
+}
+
+impl CacheCodec {
+    fn extension(&self) -> &'static str {
+        match self {
+            CacheCodec::Raw => "raw",
+            CacheCodec::Zstd { .. } => "zst",
+            CacheCodec::Xz { .. } => "xz",
+        }
+    }
+
+    fn encode(&self, data: &[u8]) -> anyhow::Result<Vec<u8>> {
+        match self {
+            CacheCodec::Raw => Ok(data.to_vec()),
+            CacheCodec::Zstd { level, window_log } => {
+                let mut encoder = zstd::stream::Encoder::new(Vec::new(), *level)
+                    .context("failed to create zstd encoder")?;
+                if let Some(window_log) = window_log {
+                    encoder
+                        .window_log(*window_log)
+                        .context("invalid zstd window_log")?;
+                }
+                encoder.write_all(data)?;
+                Ok(encoder.finish()?)
+            }
+            CacheCodec::Xz { level, dict_size } => {
+                let mut filters = xz2::stream::Filters::new();
+                let mut lzma_options = xz2::stream::LzmaOptions::new_preset(*level)
+                    .context("invalid xz compression level")?;
+                if let Some(dict_size) = dict_size {
+                    lzma_options.dict_size(*dict_size);
+                }
+                filters.lzma2(&lzma_options);
+                let stream =
+                    xz2::stream::Stream::new_stream_encoder(&filters, xz2::stream::Check::Crc32)
+                        .context("failed to create xz encoder")?;
+                let mut encoder = xz2::write::XzEncoder::new_stream(Vec::new(), stream);
+                encoder.write_all(data)?;
+                Ok(encoder.finish()?)
+            }
+        }
+    }
+
+    fn decode(&self, data: Vec<u8>) -> anyhow::Result<Vec<u8>> {
+        let mut out = Vec::new();
+        match self {
+            CacheCodec::Raw => out = data,
+            CacheCodec::Zstd { .. } => {
+                zstd::stream::Decoder::new(Cursor::new(data))?.read_to_end(&mut out)?;
+            }
+            CacheCodec::Xz { .. } => {
+                xz2::read::XzDecoder::new(Cursor::new(data)).read_to_end(&mut out)?;
+            }
+        }
+        Ok(out)
+    }
+}
+
+/// Content-addressed cache of extracted package member files, keyed by `(rpm_path, inner_file)`.
+/// Populated lazily by `get_or_populate` and bounded by `max_size`, evicting the
+/// least-recently-used entries (tracked via each blob's own mtime) once the cap is exceeded.
+pub struct ExtractCache {
+    dir: PathBuf,
+    codec: CacheCodec,
+    max_size: u64,
+    tmp_file_counter: AtomicU64,
+}
+
+impl ExtractCache {
+    pub fn new(dir: impl Into<PathBuf>, codec: CacheCodec, max_size: ByteSize) -> anyhow::Result<Self> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("could not create cache directory {dir:?}"))?;
+        Ok(ExtractCache {
+            dir,
+            codec,
+            max_size: max_size.as_u64(),
+            tmp_file_counter: AtomicU64::new(0),
+        })
+    }
+
+    /// Returns the cached, decoded bytes for `(rpm_path, inner_file)` if present, or runs
+    /// `populate` to extract them and stores the result for next time.
+    pub(crate) fn get_or_populate(
+        &self,
+        rpm_path: &str,
+        inner_file: &str,
+        populate: impl FnOnce() -> Option<Vec<u8>>,
+    ) -> Option<Vec<u8>> {
+        if let Some(data) = self.get(rpm_path, inner_file) {
+            return Some(data);
+        }
+
+        let data = populate()?;
+        if let Err(error) = self.insert(rpm_path, inner_file, &data) {
+            warn!("could not populate extraction cache for {rpm_path}: {error}");
+        }
+        Some(data)
+    }
+
+    fn get(&self, rpm_path: &str, inner_file: &str) -> Option<Vec<u8>> {
+        let path = self.entry_path(rpm_path, inner_file);
+        let mut file = File::open(&path).ok()?;
+
+        let mut encoded = Vec::new();
+        file.read_to_end(&mut encoded).ok()?;
+        // Bump the entry's mtime so eviction treats it as recently used.
+        let _ = file.set_modified(SystemTime::now());
+
+        self.codec.decode(encoded).ok()
+    }
+
+    fn insert(&self, rpm_path: &str, inner_file: &str, data: &[u8]) -> anyhow::Result<()> {
+        let encoded = self.codec.encode(data)?;
+
+        let final_path = self.entry_path(rpm_path, inner_file);
+        // Concurrent cache-miss populates for the same key (the normal thundering-herd case for
+        // a popular build-id under Rocket's multithreaded server) must not share a temp file, so
+        // suffix it with our pid and a per-process counter rather than deriving it deterministically
+        // from `final_path`.
+        let unique = self.tmp_file_counter.fetch_add(1, Ordering::Relaxed);
+        let tmp_path = final_path.with_extension(format!("tmp.{}.{}", process::id(), unique));
+        std::fs::write(&tmp_path, &encoded)?;
+        std::fs::rename(&tmp_path, &final_path)?;
+
+        self.evict_if_needed();
+        Ok(())
+    }
+
+    /// Derives the on-disk cache path for `(rpm_path, inner_file)`. The package's own
+    /// `(mtime, size)` fingerprint is folded into the key so replacing a package in place
+    /// invalidates its previously cached members instead of serving stale bytes, mirroring
+    /// how `CacheIndex` invalidates on the same fingerprint.
+    fn entry_path(&self, rpm_path: &str, inner_file: &str) -> PathBuf {
+        let mut hasher = DefaultHasher::new();
+        rpm_path.hash(&mut hasher);
+        inner_file.hash(&mut hasher);
+        file_fingerprint(rpm_path).unwrap_or((0, 0)).hash(&mut hasher);
+        self.dir
+            .join(format!("{:016x}.{}", hasher.finish(), self.codec.extension()))
+    }
+
+    /// Evicts least-recently-used entries (oldest mtime first) until the cache directory is
+    /// back under `max_size`.
+    fn evict_if_needed(&self) {
+        let mut entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok())
+                .filter_map(|entry| {
+                    let metadata = entry.metadata().ok()?;
+                    let modified = metadata.modified().ok()?;
+                    Some((entry.path(), metadata.len(), modified))
+                })
+                .collect::<Vec<_>>(),
+            Err(error) => {
+                warn!("could not list extraction cache directory: {error}");
+                return;
+            }
+        };
+
+        let mut total_size: u64 = entries.iter().map(|(_, size, _)| size).sum();
+        if total_size <= self.max_size {
+            return;
+        }
+
+        entries.sort_by_key(|(_, _, modified)| *modified);
+        for (path, size, _) in entries {
+            if total_size <= self.max_size {
+                break;
+            }
+            if std::fs::remove_file(&path).is_ok() {
+                total_size = total_size.saturating_sub(size);
+            }
+        }
+    }
+}
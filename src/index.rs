@@ -0,0 +1,110 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::RPMFile;
+
+/// Name of the index file stored directly under the scanned root.
+const INDEX_FILE_NAME: &str = ".debuginfod-rs.index";
+
+/// Bumped whenever the on-disk schema changes so a stale index triggers a full rebuild
+/// instead of being (mis)interpreted.
+const INDEX_SCHEMA_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct CachedEntry {
+    pub mtime: u64,
+    pub size: u64,
+    pub rpm_file: RPMFile,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct CacheIndex {
+    version: u32,
+    entries: HashMap<String, CachedEntry>,
+}
+
+impl CacheIndex {
+    pub(crate) fn empty() -> CacheIndex {
+        CacheIndex {
+            version: INDEX_SCHEMA_VERSION,
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Loads the index from `root_path`, falling back to an empty one if it is missing,
+    /// unreadable or written by an incompatible schema version.
+    pub(crate) fn load(root_path: &str) -> CacheIndex {
+        let path = Path::new(root_path).join(INDEX_FILE_NAME);
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(_) => return CacheIndex::empty(),
+        };
+
+        match bincode::deserialize_from::<_, CacheIndex>(BufReader::new(file)) {
+            Ok(index) if index.version == INDEX_SCHEMA_VERSION => index,
+            Ok(_) => {
+                warn!("cache index schema version mismatch, rebuilding from scratch");
+                CacheIndex::empty()
+            }
+            Err(error) => {
+                warn!("could not read cache index, rebuilding from scratch: {error}");
+                CacheIndex::empty()
+            }
+        }
+    }
+
+    /// Returns the cached entry for `path` iff its mtime and size still match, i.e. the file
+    /// has not changed since it was last analyzed.
+    pub(crate) fn lookup(&self, path: &str, mtime: u64, size: u64) -> Option<&RPMFile> {
+        self.entries.get(path).and_then(|entry| {
+            if entry.mtime == mtime && entry.size == size {
+                Some(&entry.rpm_file)
+            } else {
+                None
+            }
+        })
+    }
+
+    pub(crate) fn insert(&mut self, path: String, mtime: u64, size: u64, rpm_file: RPMFile) {
+        self.entries.insert(
+            path,
+            CachedEntry {
+                mtime,
+                size,
+                rpm_file,
+            },
+        );
+    }
+
+    /// Drops entries for files that disappeared since the last scan.
+    pub(crate) fn prune(&mut self, known_paths: &HashSet<String>) {
+        self.entries.retain(|path, _| known_paths.contains(path));
+    }
+
+    /// Writes the index back to `root_path`, via a temp file + rename so a crash mid-write
+    /// cannot leave behind a half-written, unreadable index.
+    pub(crate) fn save(&self, root_path: &str) -> anyhow::Result<()> {
+        let final_path = Path::new(root_path).join(INDEX_FILE_NAME);
+        let tmp_path = final_path.with_extension("tmp");
+
+        let file = File::create(&tmp_path)?;
+        bincode::serialize_into(BufWriter::new(file), self)?;
+        std::fs::rename(&tmp_path, &final_path)?;
+
+        Ok(())
+    }
+}
+
+/// Extracts an `(mtime, size)` fingerprint for `path`, analogous to what a build system would
+/// use to decide whether a source file needs to be recompiled.
+pub(crate) fn file_fingerprint(path: &str) -> anyhow::Result<(u64, u64)> {
+    let metadata = std::fs::metadata(path)?;
+    let mtime = metadata.modified()?.duration_since(UNIX_EPOCH)?.as_secs();
+    Ok((mtime, metadata.len()))
+}
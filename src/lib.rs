@@ -1,23 +1,24 @@
-use std::collections::HashMap;
-use std::io::{BufRead, BufReader, Read};
+use std::collections::{HashMap, HashSet};
+use std::io::Read;
 use std::sync::mpsc::channel;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
-use anyhow::{anyhow, Context};
+use anyhow::anyhow;
 use bytesize::ByteSize;
-use bzip2::bufread::BzDecoder;
-use cpio::NewcReader;
 use elf::abi::SHT_NOBITS;
 use elf::endian::AnyEndian;
 use elf::ElfBytes;
-use flate2::read::GzDecoder;
-use itertools::Itertools;
 use log::{info, warn};
-use path_absolutize::*;
 use rayon::prelude::*;
-use rpm::CompressionType;
+use serde::{Deserialize, Serialize};
 use walkdir::WalkDir;
-use xz2::read::XzDecoder;
+
+mod extract_cache;
+mod index;
+mod package;
+pub use extract_cache::{CacheCodec, ExtractCache};
+use index::{file_fingerprint, CacheIndex};
+use package::backend_for_path;
 
 extern crate log;
 
@@ -29,14 +30,22 @@ const BUILD_CHARS: usize = 20;
 
 pub type BuildId = [u8; BUILD_CHARS];
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 enum RPMKind {
     Binary,
-    DebugInfo { build_ids: HashMap<BuildId, String> },
+    DebugInfo {
+        build_ids: HashMap<BuildId, String>,
+        /// Whether `build_ids` values are the binary's install path with `DEBUG_INFO_PATH`
+        /// prepended and `.debug` appended (RPM's convention), so `get_binary_rpm_for_build_id`
+        /// can recover the binary's path by undoing that transform. Debian's dbgsym packages
+        /// store the literal build-id-indexed payload path instead, which has no such relation
+        /// to the binary's install path.
+        mirrors_binary_paths: bool,
+    },
     DebugSource,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct RPMFile {
     arch: String,
     source_rpm: String,
@@ -46,6 +55,15 @@ struct RPMFile {
     kind: RPMKind,
 }
 
+/// A single ELF section header, as found in a `(rpm_path, inner_file)`'s section header table.
+#[derive(Debug, Clone)]
+struct SectionHeaderEntry {
+    name: String,
+    sh_offset: u64,
+    sh_size: u64,
+    sh_type: u32,
+}
+
 #[derive(Debug)]
 pub struct DebugInfoRPM {
     pub rpm_path: String,
@@ -53,6 +71,8 @@ pub struct DebugInfoRPM {
     pub source_rpm: Option<String>,
 
     pub build_id_to_path: HashMap<BuildId, String>,
+    /// See `RPMKind::DebugInfo`'s field of the same name.
+    pub mirrors_binary_paths: bool,
 }
 
 pub struct Server {
@@ -61,6 +81,15 @@ pub struct Server {
 
     pub build_ids: HashMap<BuildId, Arc<DebugInfoRPM>>,
     pub total_byte_size: u64,
+
+    extract_cache: Option<ExtractCache>,
+    source_remap_rules: Vec<(String, String)>,
+
+    /// Section header tables already parsed out of a `(rpm_path, inner_file)` by
+    /// `read_rpm_file_section`, so repeat requests for that file (even for a different section)
+    /// can read the stream only up to the known `sh_offset + sh_size` instead of
+    /// re-decompressing and re-parsing the whole object to rediscover it.
+    section_header_cache: Mutex<HashMap<(String, String), Vec<SectionHeaderEntry>>>,
 }
 
 impl Server {
@@ -70,7 +99,37 @@ impl Server {
             debug_info_rpms: Vec::new(),
             build_ids: HashMap::new(),
             total_byte_size: 0,
+            extract_cache: None,
+            source_remap_rules: Vec::new(),
+            section_header_cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Enables the on-disk extraction cache for `read_rpm_file`, so that repeated requests for
+    /// a popular build-id don't re-decompress the archive from byte zero every time.
+    pub fn with_extract_cache(mut self, extract_cache: ExtractCache) -> Self {
+        self.extract_cache = Some(extract_cache);
+        self
+    }
+
+    /// Configures `from -> to` prefix rewrite rules applied by `remap_source_path`, mirroring
+    /// the `remap-path-prefix` mechanism used at compile time to normalize debug paths. Rules
+    /// are tried longest-`from`-first regardless of the order they were given in.
+    pub fn with_source_remap_rules(mut self, mut rules: Vec<(String, String)>) -> Self {
+        rules.sort_by(|(a, _), (b, _)| b.len().cmp(&a.len()));
+        self.source_remap_rules = rules;
+        self
+    }
+
+    /// Rewrites `path` using the first matching `source_remap_rules` entry (longest `from`
+    /// first), or returns it unchanged if no rule applies.
+    pub fn remap_source_path(&self, path: &str) -> String {
+        for (from, to) in &self.source_remap_rules {
+            if let Some(rest) = path.strip_prefix(from.as_str()) {
+                return format!("{to}{rest}");
+            }
         }
+        path.to_string()
     }
 
     pub fn walk(&mut self) {
@@ -78,14 +137,17 @@ impl Server {
         for entry in WalkDir::new(self.root_path.clone()) {
             let entry = entry.unwrap();
             if entry.metadata().unwrap().is_file()
-                && entry.path().extension().is_some_and(|e| e == "rpm")
+                && entry
+                    .path()
+                    .extension()
+                    .is_some_and(|e| e == "rpm" || e == "deb" || e == "ddeb")
             {
                 let path = entry.path().to_str();
                 match path {
                     Some(path) => {
                         files.push(path.to_string());
                     }
-                    None => warn!("invalid RPM file path {entry:?}"),
+                    None => warn!("invalid package file path {entry:?}"),
                 }
             }
         }
@@ -95,26 +157,38 @@ impl Server {
             .map(|f| std::fs::metadata(f).unwrap().len())
             .sum();
         info!(
-            "walking {} RPM files ({})",
+            "walking {} package files ({})",
             files.len(),
             ByteSize(self.total_byte_size)
         );
 
+        let cache = CacheIndex::load(&self.root_path);
+
         let (rx, tx) = channel();
 
         files.par_iter().for_each_with(rx, |rx, path| {
-            let _ = rx.send(self.analyze_file(path));
+            let _ = rx.send(self.analyze_file_cached(path, &cache));
         });
 
         let mut rpms = Vec::new();
+        let mut new_cache = CacheIndex::empty();
 
         for item in tx.iter() {
             match item {
-                Ok(rpm_file) => rpms.push(rpm_file),
+                Ok((path, mtime, size, rpm_file)) => {
+                    new_cache.insert(path, mtime, size, rpm_file.clone());
+                    rpms.push(rpm_file);
+                }
                 Err(error) => warn!("could not analyze RPM: {error}"),
             }
         }
 
+        let known_paths: HashSet<String> = files.into_iter().collect();
+        new_cache.prune(&known_paths);
+        if let Err(error) = new_cache.save(&self.root_path) {
+            warn!("could not write cache index: {error}");
+        }
+
         // First iterate the source RPM filies and create a map we can later use for construction
         // of the DebugInfoRPM entires.
         let mut source_rpm_map = HashMap::new();
@@ -135,7 +209,11 @@ impl Server {
 
         // Now we can construct DebugInfoRPM entries and find the corresponding Binary and DebugSource packages.
         for rpm in &rpms {
-            if let RPMKind::DebugInfo { build_ids } = &rpm.kind {
+            if let RPMKind::DebugInfo {
+                build_ids,
+                mirrors_binary_paths,
+            } = &rpm.kind
+            {
                 let debug_info = Arc::new(DebugInfoRPM {
                     rpm_path: rpm.path.clone(),
                     binary_rpm_path: binary_rpm_map
@@ -145,6 +223,7 @@ impl Server {
                         .get(&(&rpm.arch, &rpm.source_rpm))
                         .map(|r| r.path.clone()),
                     build_id_to_path: build_ids.clone(),
+                    mirrors_binary_paths: *mirrors_binary_paths,
                 });
 
                 self.debug_info_rpms.push(debug_info.clone());
@@ -156,180 +235,51 @@ impl Server {
         }
     }
 
-    fn analyze_file(&self, rpm_path: &str) -> anyhow::Result<RPMFile> {
-        let rpm_file = std::fs::File::open(rpm_path)?;
-        let mut buf_reader = std::io::BufReader::new(rpm_file);
-        let header = rpm::PackageMetadata::parse(&mut buf_reader)?;
-
-        let name = header.get_name()?;
-        let is_debug_info_rpm = name.ends_with("-debuginfo");
-        let canonical_name = name.strip_suffix("-debuginfo").unwrap_or(name).to_string();
-
-        let source_rpm = header.get_source_rpm()?.to_string();
-        let arch = header.get_arch()?.to_string();
-        let rpm_path = rpm_path.to_string();
-
-        let mut build_ids = HashMap::new();
-
-        let mut contains_dwz = false;
-        for file_entry in header.get_file_entries()? {
-            let path = file_entry.path;
-            if is_debug_info_rpm {
-                if path.starts_with(DEBUG_INFO_BUILD_ID_PATH)
-                    && path.extension().is_some_and(|e| e == "debug")
-                {
-                    let mut build_id = path
-                        .parent()
-                        .context("parent must exist")?
-                        .file_name()
-                        .context("direct name must exist")?
-                        .to_str()
-                        .context("filename should be valid")?
-                        .to_string();
-                    build_id.push_str(
-                        path.file_stem()
-                            .context("file stem expected")?
-                            .to_str()
-                            .context("valid path expected")?,
-                    );
-                    let build_id = self.parse_build_id(&build_id);
-                    match build_id {
-                        Ok(build_id) => {
-                            let target = path
-                                .parent()
-                                .context("filename must have a parent")?
-                                .join(file_entry.linkto.clone());
-                            build_ids.insert(
-                                build_id,
-                                target
-                                    .as_path()
-                                    .absolutize()?
-                                    .to_str()
-                                    .context("symlink target path must be valid")?
-                                    .to_string(),
-                            );
-                        }
-                        Err(_error) => {
-                            // warn!("{rpm_path} {path:?} {_error}");
-                        }
-                    }
-                } else if path.starts_with(DWZ_DEBUG_INFO_PATH) {
-                    contains_dwz = true;
-                }
-            }
-        }
+    /// Analyzes `rpm_path`, reusing the cached `RPMFile` from a previous `walk` if the file's
+    /// (mtime, size) fingerprint hasn't changed since it was last indexed.
+    fn analyze_file_cached(
+        &self,
+        rpm_path: &str,
+        cache: &CacheIndex,
+    ) -> anyhow::Result<(String, u64, u64, RPMFile)> {
+        let (mtime, size) = file_fingerprint(rpm_path)?;
 
-        // Right now, there is a missing symlink from a build-id to the .dwz files in the RPM container and
-        // so we need to parse it in the ELF file.
-        if contains_dwz {
-            if let Some((build_id, path)) = self.get_build_id_for_dwz(&rpm_path) {
-                build_ids.insert(build_id, path);
-            }
+        if let Some(rpm_file) = cache.lookup(rpm_path, mtime, size) {
+            return Ok((rpm_path.to_string(), mtime, size, rpm_file.clone()));
         }
 
-        let kind = if is_debug_info_rpm {
-            RPMKind::DebugInfo { build_ids }
-        } else if name.ends_with("-debugsource") {
-            RPMKind::DebugSource
-        } else {
-            RPMKind::Binary
-        };
-        Ok(RPMFile {
-            arch,
-            source_rpm,
-            name: canonical_name,
-            path: rpm_path,
-            kind,
-        })
+        let rpm_file = self.analyze_file(rpm_path)?;
+        Ok((rpm_path.to_string(), mtime, size, rpm_file))
+    }
+
+    /// Analyzes `path` with whichever `Package` backend matches its extension (RPM, Debian, ...).
+    fn analyze_file(&self, path: &str) -> anyhow::Result<RPMFile> {
+        backend_for_path(path)?.analyze(path)
     }
 
+    /// Opens a stream positioned at the first member file accepted by `file_selector`, again
+    /// dispatching to the `Package` backend that matches `path`'s extension.
     fn get_rpm_file_stream(
         &self,
         path: &str,
         file_selector: impl Fn(&str) -> bool,
-    ) -> anyhow::Result<(NewcReader<impl Read>, String)> {
-        let rpm_file = std::fs::File::open(path).context("cannot open RPM file")?;
-
-        let mut buf_reader = std::io::BufReader::new(rpm_file);
-        let header = rpm::PackageMetadata::parse(&mut buf_reader)?;
-        let compressor = header.get_payload_compressor();
-        let mut decoder: Box<dyn BufRead> = match compressor? {
-            CompressionType::Zstd => Box::new(BufReader::new(
-                zstd::stream::Decoder::new(buf_reader).context("ZSTD decoded failed")?,
-            )),
-            CompressionType::Gzip => Box::new(BufReader::new(GzDecoder::new(buf_reader))),
-            CompressionType::Bzip2 => Box::new(BufReader::new(BzDecoder::new(buf_reader))),
-            CompressionType::Xz => Box::new(BufReader::new(XzDecoder::new(buf_reader))),
-            CompressionType::None => Box::new(buf_reader),
-        };
-
-        loop {
-            let archive = NewcReader::new(decoder).context("CPIO decoder failed")?;
-            let entry = archive.entry();
-            if entry.is_trailer() {
-                break;
-            }
-            let mut name = entry.name().to_string();
-            if name.starts_with('.') {
-                name = String::from_iter(name.chars().skip(1));
-            }
-            let file_size = entry.file_size() as usize;
-
-            if file_selector(&name) && file_size > 0 {
-                return Ok((archive, name.clone()));
-            } else {
-                decoder = archive.finish().unwrap();
-            }
-        }
-
-        Err(anyhow!("file not found in the archive"))
-    }
-
-    fn get_build_id_for_dwz(&self, file: &str) -> Option<(BuildId, String)> {
-        // For now, let's parse '.note.gnu.build-id' section without any ELF library.
-        // Luckily, the created .dwz files (e.g. /usr/lib/debug/.dwz/foo.x86_64) have only a limited
-        // number of ELF sections and the note is section is at the very beginning.
-        //
-        // See SHT_NOTE for a more detail specification. Our note contains "GNU\0" followed by the Build-Id.
-
-        if let Ok((mut stream, name)) =
-            self.get_rpm_file_stream(file, |name| name.starts_with(DWZ_DEBUG_INFO_PATH))
-        {
-            let mut data = vec![0; 256];
-            let _ = stream.read_exact(&mut data);
-            let mut heystack = data.as_slice();
-            for _ in 0..(data.len() - BUILD_ID_ELF_PREFIX.len() - BUILD_CHARS) {
-                if heystack.starts_with(&BUILD_ID_ELF_PREFIX) {
-                    let build_id = heystack
-                        .iter()
-                        .skip(BUILD_ID_ELF_PREFIX.len())
-                        .take(BUILD_CHARS)
-                        .copied()
-                        .collect_vec();
-                    let build_id = BuildId::try_from(build_id);
-                    if let Ok(build_id) = build_id {
-                        return Some((build_id, name));
-                    } else {
-                        break;
-                    }
-                } else {
-                    // Shift the heystack by one byte and continue
-                    heystack = &heystack[1..];
-                }
-            }
-        }
-
-        None
+    ) -> anyhow::Result<(Box<dyn Read>, String)> {
+        backend_for_path(path)?.open_file_stream(path, &file_selector)
     }
 
     pub fn get_binary_rpm_for_build_id(&self, build_id: &BuildId) -> Option<(String, String)> {
         if let Some(debug_info_rpm) = self.build_ids.get(build_id) {
+            // Only RPM's debug-info packages store a build-id path that mirrors the binary's
+            // install path; Debian's dbgsym packages store the literal build-id-indexed payload
+            // path instead, which has no such relation to recover the binary's filename from.
+            if !debug_info_rpm.mirrors_binary_paths {
+                return None;
+            }
+
             if let Some(filename) = debug_info_rpm.build_id_to_path.get(build_id) {
                 let filename = filename
-                    .strip_suffix(".debug")
-                    .unwrap()
-                    .strip_prefix(DEBUG_INFO_PATH)
-                    .unwrap()
+                    .strip_suffix(".debug")?
+                    .strip_prefix(DEBUG_INFO_PATH)?
                     .to_string();
                 if let Some(binary_rpm_path) = &debug_info_rpm.binary_rpm_path {
                     return Some((binary_rpm_path.clone(), filename));
@@ -341,6 +291,15 @@ impl Server {
     }
 
     pub fn read_rpm_file(&self, rpm_file: &str, file: &str) -> Option<Vec<u8>> {
+        match &self.extract_cache {
+            Some(cache) => {
+                cache.get_or_populate(rpm_file, file, || self.read_rpm_file_uncached(rpm_file, file))
+            }
+            None => self.read_rpm_file_uncached(rpm_file, file),
+        }
+    }
+
+    fn read_rpm_file_uncached(&self, rpm_file: &str, file: &str) -> Option<Vec<u8>> {
         info!("reading RPM file {rpm_file}");
         if let Ok((mut stream, _)) = self.get_rpm_file_stream(rpm_file, |f| f == file) {
             info!("found RPM file: {file}");
@@ -352,40 +311,107 @@ impl Server {
         }
     }
 
+    /// Reads the bytes of a single ELF section out of `file` inside `rpm_file`.
+    ///
+    /// The section header table lives at `e_shoff`, i.e. at the *end* of the object, so finding
+    /// it at all requires decompressing essentially the whole file; there's no way to "probe"
+    /// for it cheaply on a sequential CPIO/archive stream. The only real amortization strategy
+    /// is to pay that cost once and remember the result: the first request for a given
+    /// `(rpm_file, file)` reads it fully (no worse than before) and caches every section's
+    /// `sh_offset`/`sh_size`/`sh_type`; every subsequent request - for this section or any other
+    /// one in the same file - already knows `sh_offset` and can read only up to
+    /// `sh_offset + sh_size`, skipping the tail of the file entirely.
     pub fn read_rpm_file_section(
         &self,
         rpm_file: &str,
         file: &str,
         section: &str,
     ) -> Option<Vec<u8>> {
-        if let Some(data) = self.read_rpm_file(rpm_file, file) {
-            if let Ok(elf_file) = ElfBytes::<AnyEndian>::minimal_parse(data.as_slice()) {
-                if let Ok(section) = elf_file.section_header_by_name(section) {
-                    let section = section?;
-                    if section.sh_type == SHT_NOBITS {
-                        return None;
-                    }
+        let key = (rpm_file.to_string(), file.to_string());
 
-                    if let Ok(section_data) = elf_file.section_data(&section) {
-                        let mut result = Vec::new();
-                        section_data.0.clone_into(&mut result);
-                        return Some(result);
-                    }
-                }
-            }
+        if let Some(entry) = self.cached_section_header(&key, section) {
+            return self.read_section_bounded(rpm_file, file, &entry);
         }
-        None
+
+        let data = self.read_rpm_file(rpm_file, file)?;
+        let elf_file = ElfBytes::<AnyEndian>::minimal_parse(data.as_slice()).ok()?;
+        let (shdrs, strtab) = elf_file.section_headers_with_strtab().ok()?;
+        let (shdrs, strtab) = (shdrs?, strtab?);
+
+        let entries: Vec<SectionHeaderEntry> = shdrs
+            .iter()
+            .filter_map(|shdr| {
+                let name = strtab.get(shdr.sh_name as usize).ok()?;
+                Some(SectionHeaderEntry {
+                    name: name.to_string(),
+                    sh_offset: shdr.sh_offset,
+                    sh_size: shdr.sh_size,
+                    sh_type: shdr.sh_type,
+                })
+            })
+            .collect();
+
+        let wanted = entries.iter().find(|entry| entry.name == section).cloned();
+        self.section_header_cache.lock().unwrap().insert(key, entries);
+
+        let wanted = wanted?;
+        if wanted.sh_type == SHT_NOBITS {
+            return None;
+        }
+
+        let start = wanted.sh_offset as usize;
+        let end = start + wanted.sh_size as usize;
+        data.get(start..end).map(|slice| slice.to_vec())
     }
 
-    pub fn parse_build_id(&self, id: &str) -> anyhow::Result<BuildId> {
-        let array = hex::decode(id)?;
-        if array.len() != BUILD_CHARS {
-            Err(anyhow!(
-                "Invalid build-id length: {}, expected {BUILD_CHARS}",
-                array.len()
-            ))
-        } else {
-            Ok(BuildId::try_from(array.as_slice())?)
+    fn cached_section_header(
+        &self,
+        key: &(String, String),
+        section: &str,
+    ) -> Option<SectionHeaderEntry> {
+        self.section_header_cache
+            .lock()
+            .unwrap()
+            .get(key)?
+            .iter()
+            .find(|entry| entry.name == section)
+            .cloned()
+    }
+
+    /// Reads only the bytes needed for `entry` out of `rpm_file`/`file`, relying on a
+    /// previously cached section header table to know exactly how far to read.
+    fn read_section_bounded(
+        &self,
+        rpm_file: &str,
+        file: &str,
+        entry: &SectionHeaderEntry,
+    ) -> Option<Vec<u8>> {
+        if entry.sh_type == SHT_NOBITS {
+            return None;
         }
+
+        let (mut stream, _) = self.get_rpm_file_stream(rpm_file, |f| f == file).ok()?;
+        let needed = entry.sh_offset as usize + entry.sh_size as usize;
+
+        let mut buffer = vec![0; needed];
+        stream.read_exact(&mut buffer).ok()?;
+
+        Some(buffer[entry.sh_offset as usize..needed].to_vec())
+    }
+
+    pub fn parse_build_id(&self, id: &str) -> anyhow::Result<BuildId> {
+        parse_build_id(id)
+    }
+}
+
+pub(crate) fn parse_build_id(id: &str) -> anyhow::Result<BuildId> {
+    let array = hex::decode(id)?;
+    if array.len() != BUILD_CHARS {
+        Err(anyhow!(
+            "Invalid build-id length: {}, expected {BUILD_CHARS}",
+            array.len()
+        ))
+    } else {
+        Ok(BuildId::try_from(array.as_slice())?)
     }
 }
@@ -15,6 +15,63 @@ use rocket::State;
 
 use debuginfod_rs::*;
 
+/// Builds the extraction cache from environment variables, mirroring the env-driven
+/// configuration `env_logger` already uses for the log level. The cache is disabled unless
+/// `DEBUGINFOD_CACHE_DIR` is set.
+fn extract_cache_from_env() -> Option<ExtractCache> {
+    let dir = env::var("DEBUGINFOD_CACHE_DIR").ok()?;
+
+    let codec = match env::var("DEBUGINFOD_CACHE_CODEC").as_deref().unwrap_or("zstd") {
+        "raw" => CacheCodec::Raw,
+        "xz" => CacheCodec::Xz {
+            level: env::var("DEBUGINFOD_CACHE_LEVEL")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(6),
+            dict_size: env::var("DEBUGINFOD_CACHE_DICT_SIZE")
+                .ok()
+                .and_then(|v| v.parse().ok()),
+        },
+        _ => CacheCodec::Zstd {
+            level: env::var("DEBUGINFOD_CACHE_LEVEL")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(3),
+            window_log: env::var("DEBUGINFOD_CACHE_WINDOW_LOG")
+                .ok()
+                .and_then(|v| v.parse().ok()),
+        },
+    };
+
+    let max_size = env::var("DEBUGINFOD_CACHE_MAX_SIZE")
+        .ok()
+        .and_then(|v| v.parse::<ByteSize>().ok())
+        .unwrap_or(ByteSize::gb(10));
+
+    match ExtractCache::new(dir, codec, max_size) {
+        Ok(cache) => Some(cache),
+        Err(error) => {
+            eprintln!("could not set up extraction cache: {error}");
+            process::exit(1);
+        }
+    }
+}
+
+/// Parses `DEBUGINFOD_SOURCE_REMAP`, a comma-separated list of `from=to` prefix rewrite rules
+/// for the `/source` route, e.g. `/builddir/build/BUILD=/usr/src/debug/foo`.
+fn source_remap_rules_from_env() -> Vec<(String, String)> {
+    env::var("DEBUGINFOD_SOURCE_REMAP")
+        .ok()
+        .map(|value| {
+            value
+                .split(',')
+                .filter_map(|rule| rule.split_once('='))
+                .map(|(from, to)| (from.to_string(), to.to_string()))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
 #[get("/")]
 fn index() -> &'static str {
     "Welcome to debuginfod-rs server!"
@@ -75,6 +132,7 @@ fn source(build_id: String, source_path: PathBuf, state: &State<Server>) -> Opti
                 let mut filename = source_path.to_str().unwrap().to_string();
                 // Prefix all paths with slash.
                 filename.insert(0, '/');
+                let filename = state.remap_source_path(&filename);
                 return state.read_rpm_file(source_rpm_path, &filename);
             }
         }
@@ -97,6 +155,10 @@ fn rocket() -> _ {
 
     let start = Instant::now();
     let mut server = Server::new(arguments.get(1).unwrap());
+    if let Some(extract_cache) = extract_cache_from_env() {
+        server = server.with_extract_cache(extract_cache);
+    }
+    server = server.with_source_remap_rules(source_remap_rules_from_env());
     server.walk();
 
     // trim heap allocation after we parse all the RPM files
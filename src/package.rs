@@ -0,0 +1,390 @@
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Cursor, Read};
+use std::path::Path;
+
+use anyhow::{anyhow, Context};
+use bzip2::bufread::BzDecoder;
+use cpio::NewcReader;
+use flate2::read::GzDecoder;
+use itertools::Itertools;
+use path_absolutize::*;
+use rpm::CompressionType;
+use xz2::read::XzDecoder;
+
+use crate::{
+    parse_build_id, BuildId, RPMFile, RPMKind, BUILD_CHARS, BUILD_ID_ELF_PREFIX,
+    DEBUG_INFO_BUILD_ID_PATH, DWZ_DEBUG_INFO_PATH,
+};
+
+/// A package archive format that `Server` can index for build-ids and read member files out of.
+/// `backend_for_path` picks an implementation by file extension, so supporting a new distro's
+/// archive format is just a matter of adding another impl here.
+pub(crate) trait Package {
+    fn analyze(&self, path: &str) -> anyhow::Result<RPMFile>;
+
+    fn open_file_stream(
+        &self,
+        path: &str,
+        file_selector: &dyn Fn(&str) -> bool,
+    ) -> anyhow::Result<(Box<dyn Read>, String)>;
+}
+
+pub(crate) fn backend_for_path(path: &str) -> anyhow::Result<Box<dyn Package>> {
+    match Path::new(path).extension().and_then(|e| e.to_str()) {
+        Some("rpm") => Ok(Box::new(RpmPackage)),
+        Some("deb") | Some("ddeb") => Ok(Box::new(DebPackage)),
+        other => Err(anyhow!("unsupported package format: {other:?}")),
+    }
+}
+
+pub(crate) struct RpmPackage;
+
+impl Package for RpmPackage {
+    fn analyze(&self, rpm_path: &str) -> anyhow::Result<RPMFile> {
+        let rpm_file = std::fs::File::open(rpm_path)?;
+        let mut buf_reader = std::io::BufReader::new(rpm_file);
+        let header = rpm::PackageMetadata::parse(&mut buf_reader)?;
+
+        let name = header.get_name()?;
+        let is_debug_info_rpm = name.ends_with("-debuginfo");
+        let canonical_name = name.strip_suffix("-debuginfo").unwrap_or(name).to_string();
+
+        let source_rpm = header.get_source_rpm()?.to_string();
+        let arch = header.get_arch()?.to_string();
+        let rpm_path = rpm_path.to_string();
+
+        let mut build_ids = HashMap::new();
+
+        let mut contains_dwz = false;
+        for file_entry in header.get_file_entries()? {
+            let path = file_entry.path;
+            if is_debug_info_rpm {
+                if path.starts_with(DEBUG_INFO_BUILD_ID_PATH)
+                    && path.extension().is_some_and(|e| e == "debug")
+                {
+                    let mut build_id = path
+                        .parent()
+                        .context("parent must exist")?
+                        .file_name()
+                        .context("direct name must exist")?
+                        .to_str()
+                        .context("filename should be valid")?
+                        .to_string();
+                    build_id.push_str(
+                        path.file_stem()
+                            .context("file stem expected")?
+                            .to_str()
+                            .context("valid path expected")?,
+                    );
+                    let build_id = parse_build_id(&build_id);
+                    match build_id {
+                        Ok(build_id) => {
+                            let target = path
+                                .parent()
+                                .context("filename must have a parent")?
+                                .join(file_entry.linkto.clone());
+                            build_ids.insert(
+                                build_id,
+                                target
+                                    .as_path()
+                                    .absolutize()?
+                                    .to_str()
+                                    .context("symlink target path must be valid")?
+                                    .to_string(),
+                            );
+                        }
+                        Err(_error) => {
+                            // warn!("{rpm_path} {path:?} {_error}");
+                        }
+                    }
+                } else if path.starts_with(DWZ_DEBUG_INFO_PATH) {
+                    contains_dwz = true;
+                }
+            }
+        }
+
+        // Right now, there is a missing symlink from a build-id to the .dwz files in the RPM container and
+        // so we need to parse it in the ELF file.
+        if contains_dwz {
+            if let Some((build_id, path)) = build_id_for_dwz(&rpm_path) {
+                build_ids.insert(build_id, path);
+            }
+        }
+
+        let kind = if is_debug_info_rpm {
+            RPMKind::DebugInfo {
+                build_ids,
+                mirrors_binary_paths: true,
+            }
+        } else if name.ends_with("-debugsource") {
+            RPMKind::DebugSource
+        } else {
+            RPMKind::Binary
+        };
+        Ok(RPMFile {
+            arch,
+            source_rpm,
+            name: canonical_name,
+            path: rpm_path,
+            kind,
+        })
+    }
+
+    fn open_file_stream(
+        &self,
+        path: &str,
+        file_selector: &dyn Fn(&str) -> bool,
+    ) -> anyhow::Result<(Box<dyn Read>, String)> {
+        open_rpm_stream(path, file_selector)
+    }
+}
+
+fn open_rpm_stream(
+    path: &str,
+    file_selector: &dyn Fn(&str) -> bool,
+) -> anyhow::Result<(Box<dyn Read>, String)> {
+    let rpm_file = std::fs::File::open(path).context("cannot open RPM file")?;
+
+    let mut buf_reader = BufReader::new(rpm_file);
+    let header = rpm::PackageMetadata::parse(&mut buf_reader)?;
+    let compressor = header.get_payload_compressor();
+    let mut decoder: Box<dyn BufRead> = match compressor? {
+        CompressionType::Zstd => Box::new(BufReader::new(
+            zstd::stream::Decoder::new(buf_reader).context("ZSTD decoded failed")?,
+        )),
+        CompressionType::Gzip => Box::new(BufReader::new(GzDecoder::new(buf_reader))),
+        CompressionType::Bzip2 => Box::new(BufReader::new(BzDecoder::new(buf_reader))),
+        CompressionType::Xz => Box::new(BufReader::new(XzDecoder::new(buf_reader))),
+        CompressionType::None => Box::new(buf_reader),
+    };
+
+    loop {
+        let archive = NewcReader::new(decoder).context("CPIO decoder failed")?;
+        let entry = archive.entry();
+        if entry.is_trailer() {
+            break;
+        }
+        let mut name = entry.name().to_string();
+        if name.starts_with('.') {
+            name = String::from_iter(name.chars().skip(1));
+        }
+        let file_size = entry.file_size() as usize;
+
+        if file_selector(&name) && file_size > 0 {
+            return Ok((Box::new(archive), name));
+        } else {
+            decoder = archive.finish().unwrap();
+        }
+    }
+
+    Err(anyhow!("file not found in the archive"))
+}
+
+fn build_id_for_dwz(file: &str) -> Option<(BuildId, String)> {
+    // For now, let's parse '.note.gnu.build-id' section without any ELF library.
+    // Luckily, the created .dwz files (e.g. /usr/lib/debug/.dwz/foo.x86_64) have only a limited
+    // number of ELF sections and the note is section is at the very beginning.
+    //
+    // See SHT_NOTE for a more detail specification. Our note contains "GNU\0" followed by the Build-Id.
+
+    if let Ok((mut stream, name)) =
+        open_rpm_stream(file, &|name| name.starts_with(DWZ_DEBUG_INFO_PATH))
+    {
+        let mut data = vec![0; 256];
+        let _ = stream.read_exact(&mut data);
+        let mut heystack = data.as_slice();
+        for _ in 0..(data.len() - BUILD_ID_ELF_PREFIX.len() - BUILD_CHARS) {
+            if heystack.starts_with(&BUILD_ID_ELF_PREFIX) {
+                let build_id = heystack
+                    .iter()
+                    .skip(BUILD_ID_ELF_PREFIX.len())
+                    .take(BUILD_CHARS)
+                    .copied()
+                    .collect_vec();
+                let build_id = BuildId::try_from(build_id);
+                if let Ok(build_id) = build_id {
+                    return Some((build_id, name));
+                } else {
+                    break;
+                }
+            } else {
+                // Shift the heystack by one byte and continue
+                heystack = &heystack[1..];
+            }
+        }
+    }
+
+    None
+}
+
+/// Debian `.deb`/`.ddeb` backend. An archive is an `ar(1)` file whose members are
+/// `control.tar.{gz,xz,zst}` (package metadata) and `data.tar.{gz,xz,zst}` (payload); debug
+/// ELFs live in the latter under `/usr/lib/debug/.build-id/xx/rest.debug`, exactly like RPM.
+pub(crate) struct DebPackage;
+
+impl Package for DebPackage {
+    fn analyze(&self, deb_path: &str) -> anyhow::Result<RPMFile> {
+        let (control_member, control_member_name) = read_ar_member(deb_path, "control.tar")?;
+        let control_tar = decompress_tar_member(control_member, &control_member_name)?;
+        let (control_bytes, _) =
+            read_tar_member(&control_tar, |path| {
+                path == "/control" || Path::new(path).file_name().is_some_and(|n| n == "control")
+            })
+            .context("control file missing from control.tar")?;
+        let control = String::from_utf8_lossy(&control_bytes);
+
+        let name = control_field(&control, "Package:")
+            .context("control file is missing a Package: field")?
+            .to_string();
+        let arch = control_field(&control, "Architecture:")
+            .unwrap_or("all")
+            .to_string();
+        let source_rpm = control_field(&control, "Source:")
+            .and_then(|value| value.split_whitespace().next())
+            .unwrap_or(&name)
+            .to_string();
+
+        // Debian's debug packages follow the `dbgsym` naming convention rather than rpm's
+        // `-debuginfo`; there is no Debian equivalent of a separate `-debugsource` package.
+        let is_debug_info = name.ends_with("-dbgsym");
+        let canonical_name = name.strip_suffix("-dbgsym").unwrap_or(&name).to_string();
+
+        let kind = if is_debug_info {
+            let (data_member, data_member_name) = read_ar_member(deb_path, "data.tar")?;
+            let data_tar = decompress_tar_member(data_member, &data_member_name)?;
+            RPMKind::DebugInfo {
+                build_ids: collect_build_ids(&data_tar)?,
+                // collect_build_ids stores the literal build-id-indexed payload path (there is
+                // no Debian equivalent of RPM's mirrored-path symlink), so it cannot be turned
+                // back into the binary's install path.
+                mirrors_binary_paths: false,
+            }
+        } else {
+            RPMKind::Binary
+        };
+
+        Ok(RPMFile {
+            arch,
+            source_rpm,
+            name: canonical_name,
+            path: deb_path.to_string(),
+            kind,
+        })
+    }
+
+    fn open_file_stream(
+        &self,
+        path: &str,
+        file_selector: &dyn Fn(&str) -> bool,
+    ) -> anyhow::Result<(Box<dyn Read>, String)> {
+        let (data_member, data_member_name) = read_ar_member(path, "data.tar")?;
+        let data_tar = decompress_tar_member(data_member, &data_member_name)?;
+        let (data, member_name) = read_tar_member(&data_tar, file_selector)?;
+        Ok((Box::new(Cursor::new(data)), member_name))
+    }
+}
+
+/// Reads the first `ar` member whose name starts with `prefix` (e.g. `"control.tar"`) fully
+/// into memory, returning its raw (still compressed) bytes and member name.
+fn read_ar_member(path: &str, prefix: &str) -> anyhow::Result<(Vec<u8>, String)> {
+    let file = std::fs::File::open(path).context("cannot open deb file")?;
+    let mut archive = ar::Archive::new(file);
+    while let Some(entry) = archive.next_entry() {
+        let mut entry = entry.context("invalid ar entry")?;
+        let name = String::from_utf8_lossy(entry.header().identifier()).to_string();
+        if name.starts_with(prefix) {
+            let mut data = Vec::new();
+            entry.read_to_end(&mut data)?;
+            return Ok((data, name));
+        }
+    }
+
+    Err(anyhow!("{prefix} member not found in {path}"))
+}
+
+/// Decompresses a `control.tar.*`/`data.tar.*` ar member based on its file extension, reusing
+/// the same codecs the RPM backend uses for its CPIO payload.
+fn decompress_tar_member(data: Vec<u8>, member_name: &str) -> anyhow::Result<Vec<u8>> {
+    let cursor = Cursor::new(data);
+    let mut decoder: Box<dyn Read> = if member_name.ends_with(".gz") {
+        Box::new(GzDecoder::new(cursor))
+    } else if member_name.ends_with(".xz") {
+        Box::new(XzDecoder::new(cursor))
+    } else if member_name.ends_with(".zst") {
+        Box::new(zstd::stream::Decoder::new(cursor).context("ZSTD decode failed")?)
+    } else if member_name.ends_with(".bz2") {
+        Box::new(BzDecoder::new(cursor))
+    } else {
+        Box::new(cursor)
+    };
+
+    let mut tar_bytes = Vec::new();
+    decoder.read_to_end(&mut tar_bytes)?;
+    Ok(tar_bytes)
+}
+
+/// Finds the first tar member whose path matches `selector`, e.g. `|p| p == "/control"`. Tar
+/// paths are normalized the same way the RPM backend normalizes CPIO entry names: a single
+/// leading `.` (as in `./control`) is stripped so selectors can use absolute-looking paths.
+fn read_tar_member(
+    tar_bytes: &[u8],
+    selector: impl Fn(&str) -> bool,
+) -> anyhow::Result<(Vec<u8>, String)> {
+    let mut archive = tar::Archive::new(Cursor::new(tar_bytes));
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let mut name = entry.path()?.to_string_lossy().to_string();
+        if name.starts_with('.') {
+            name = String::from_iter(name.chars().skip(1));
+        }
+
+        if selector(&name) {
+            let mut data = Vec::new();
+            entry.read_to_end(&mut data)?;
+            return Ok((data, name));
+        }
+    }
+
+    Err(anyhow!("file not found in the archive"))
+}
+
+fn collect_build_ids(data_tar: &[u8]) -> anyhow::Result<HashMap<BuildId, String>> {
+    let mut build_ids = HashMap::new();
+
+    let mut archive = tar::Archive::new(Cursor::new(data_tar));
+    for entry in archive.entries()? {
+        let entry = entry?;
+        let mut path = entry.path()?.to_string_lossy().to_string();
+        if path.starts_with('.') {
+            path = String::from_iter(path.chars().skip(1));
+        }
+        let path = Path::new(&path);
+
+        if path.starts_with(DEBUG_INFO_BUILD_ID_PATH) && path.extension().is_some_and(|e| e == "debug")
+        {
+            let build_id = path
+                .parent()
+                .and_then(|p| p.file_name())
+                .and_then(|s| s.to_str())
+                .zip(path.file_stem().and_then(|s| s.to_str()))
+                .map(|(dir, stem)| format!("{dir}{stem}"));
+
+            if let Some(build_id) = build_id.and_then(|id| parse_build_id(&id).ok()) {
+                build_ids.insert(
+                    build_id,
+                    path.to_str().context("build-id path must be valid")?.to_string(),
+                );
+            }
+        }
+    }
+
+    Ok(build_ids)
+}
+
+/// Extracts the value of a `Key:` field from a Debian control file, e.g.
+/// `control_field(control, "Package:")`.
+fn control_field<'a>(control: &'a str, key: &str) -> Option<&'a str> {
+    control
+        .lines()
+        .find_map(|line| line.strip_prefix(key).map(str::trim))
+}